@@ -1,12 +1,36 @@
 use serde::{Serialize, Deserialize};
 use candid::{Decode, Encode};
 use ic_cdk::api::time;
+use ic_cdk_timers::TimerId;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
-use std::{borrow::Cow, cell::RefCell};
+use std::{borrow::Cow, cell::RefCell, time::Duration};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = Cell<u64, Memory>;
+type PeriodCell = Cell<u64, Memory>;
+type SchemaVersionCell = Cell<u32, Memory>;
+
+// Fallback aggregation period used until `init` runs or for deployments that
+// predate the configurable init arg.
+const DEFAULT_AGGREGATION_PERIOD_SECS: u64 = 3600;
+
+// Largest period that can be converted to nanoseconds without overflowing
+// the u64 multiply in `run_aggregation`.
+const MAX_AGGREGATION_PERIOD_SECS: u64 = u64::MAX / 1_000_000_000;
+
+// Fixed width reserved for `device_id` inside composite stable-memory keys so
+// entries sort and range-scan by device prefix.
+const DEVICE_ID_KEY_LEN: usize = 64;
+
+// Sentinel stored in LAST_AGGREGATED_ID before the first aggregation tick
+// has run.
+const UNSET_LAST_AGGREGATED_ID: u64 = u64::MAX;
+
+// Bump this whenever `SensorData` (or another record's on-stable-memory
+// shape) gains/changes fields, and extend `migrate()` to backfill the new
+// shape from whatever the stored version implies.
+const CURRENT_SCHEMA_VERSION: u32 = 1;
 
 // Struct to represent IoT data from a greenhouse sensor.
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
@@ -54,6 +78,413 @@ thread_local! {
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
     ));
+
+    // Configured aggregation period in seconds, persisted so it survives upgrades.
+    static AGGREGATION_PERIOD: RefCell<PeriodCell> = RefCell::new(
+        PeriodCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))),
+            DEFAULT_AGGREGATION_PERIOD_SECS,
+        )
+        .expect("Cannot create aggregation period cell")
+    );
+
+    // Per-device, per-bucket rollups produced by the periodic aggregation timer.
+    static ROLLUPS: RefCell<StableBTreeMap<RollupKey, RollupRecord, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
+    ));
+
+    // Handle of the running periodic aggregation timer, so it can be cancelled
+    // and rescheduled when the period changes. Timers don't persist across
+    // upgrades, so this is plain (non-stable) thread-local state.
+    static AGGREGATION_TIMER: RefCell<Option<TimerId>> = RefCell::new(None);
+
+    // Secondary index over (device_id, id), maintained alongside STORAGE, so
+    // a device's readings can be range-scanned without walking the full map.
+    static DEVICE_INDEX: RefCell<StableBTreeMap<DeviceIndexKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+    ));
+
+    // Per-device alert rules, keyed by rule id.
+    static ALERT_RULES: RefCell<StableBTreeMap<u64, AlertRule, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+    ));
+
+    // ID counter for alert rules.
+    static ALERT_RULE_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))), 0)
+            .expect("Cannot create alert rule ID counter")
+    );
+
+    // Log of raised alerts, keyed by (device_id, timestamp, alert_id).
+    static ALERTS: RefCell<StableBTreeMap<AlertKey, Alert, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+    ));
+
+    // ID counter for alerts.
+    static ALERT_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))), 0)
+            .expect("Cannot create alert ID counter")
+    );
+
+    // Version of the on-stable-memory record shapes, so `post_upgrade` can
+    // tell whether `migrate()` needs to rewrite existing records.
+    static SCHEMA_VERSION: RefCell<SchemaVersionCell> = RefCell::new(
+        SchemaVersionCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))), 0)
+            .expect("Cannot create schema version cell")
+    );
+
+    // Highest sensor-data id folded into ROLLUPS so far. Lets `run_aggregation`
+    // scan only readings inserted since the last tick instead of the whole
+    // STORAGE map every period. UNSET_LAST_AGGREGATED_ID means no tick has run
+    // yet, since 0 is itself a valid sensor-data id.
+    static LAST_AGGREGATED_ID: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))), UNSET_LAST_AGGREGATED_ID)
+            .expect("Cannot create last aggregated id cell")
+    );
+
+    // Secondary index over (device_id, rule_id), maintained alongside
+    // ALERT_RULES, so `evaluate_alert_rules` can look up a device's rules
+    // without scanning every rule in the canister.
+    static ALERT_RULE_INDEX: RefCell<StableBTreeMap<DeviceIndexKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
+    ));
+}
+
+// Encodes `device_id` followed by zero or more big-endian u64 suffix fields
+// into a fixed-width byte key: DEVICE_ID_KEY_LEN bytes for the device id
+// (zero-padded, never truncated) then 8 bytes per suffix. Shared by every
+// composite `(device_id, ...)` stable-memory key (`DeviceIndexKey`,
+// `RollupKey`, `AlertKey`) so the encoding only needs to be correct in one
+// place. Entries for the same device sort contiguously, then by suffix, so
+// range scans by device prefix are cheap.
+fn encode_device_keyed(device_id: &str, suffixes: &[u64]) -> Vec<u8> {
+    let device_bytes = device_id.as_bytes();
+    assert!(
+        device_bytes.len() <= DEVICE_ID_KEY_LEN,
+        "device_id exceeds the {}-byte key limit enforced by validate_sensor_data_payload",
+        DEVICE_ID_KEY_LEN
+    );
+    // Never truncate: two ids sharing a truncated prefix would otherwise
+    // collide onto the same key.
+    let mut bytes = vec![0u8; DEVICE_ID_KEY_LEN + suffixes.len() * 8];
+    bytes[..device_bytes.len()].copy_from_slice(device_bytes);
+    for (i, suffix) in suffixes.iter().enumerate() {
+        let start = DEVICE_ID_KEY_LEN + i * 8;
+        bytes[start..start + 8].copy_from_slice(&suffix.to_be_bytes());
+    }
+    bytes
+}
+
+// Inverse of `encode_device_keyed`: splits off the device id and returns the
+// remaining suffix bytes for the caller to decode.
+fn decode_device_keyed(bytes: &[u8]) -> (String, &[u8]) {
+    let device_id_end = bytes[..DEVICE_ID_KEY_LEN]
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(DEVICE_ID_KEY_LEN);
+    let device_id = String::from_utf8(bytes[..device_id_end].to_vec())
+        .expect("device_id key bytes are not valid UTF-8");
+    (device_id, &bytes[DEVICE_ID_KEY_LEN..])
+}
+
+// Composite key for the device_id -> id secondary index, so entries for the
+// same device sort contiguously and in id order, making range scans cheap.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct DeviceIndexKey {
+    device_id: String,
+    id: u64,
+}
+
+impl Storable for DeviceIndexKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(encode_device_keyed(&self.device_id, &[self.id]))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let (device_id, suffix) = decode_device_keyed(bytes.as_ref());
+        let id = u64::from_be_bytes(suffix.try_into().unwrap());
+        Self { device_id, id }
+    }
+}
+
+impl BoundedStorable for DeviceIndexKey {
+    const MAX_SIZE: u32 = (DEVICE_ID_KEY_LEN + 8) as u32;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// Composite key for a rollup bucket: device id plus the bucket's start
+// timestamp (nanoseconds), so entries for the same device sort contiguously
+// and in bucket order.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct RollupKey {
+    device_id: String,
+    bucket_start: u64,
+}
+
+impl Storable for RollupKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(encode_device_keyed(&self.device_id, &[self.bucket_start]))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let (device_id, suffix) = decode_device_keyed(bytes.as_ref());
+        let bucket_start = u64::from_be_bytes(suffix.try_into().unwrap());
+        Self { device_id, bucket_start }
+    }
+}
+
+impl BoundedStorable for RollupKey {
+    const MAX_SIZE: u32 = (DEVICE_ID_KEY_LEN + 8) as u32;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// Compacted period averages for one device/bucket, computed by folding raw
+// `SensorData` rows that fall within `[bucket_start, bucket_start + period)`.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct RollupRecord {
+    device_id: String,
+    bucket_start: u64,
+    avg_temperature: f64,
+    avg_humidity: f64,
+    avg_soil_moisture: f64,
+    sample_count: u64,
+}
+
+impl Storable for RollupRecord {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for RollupRecord {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Init argument controlling how often raw readings are folded into rollups.
+#[derive(candid::CandidType, Deserialize, Serialize)]
+struct InitArg {
+    aggregation_period_secs: u64,
+}
+
+#[ic_cdk::init]
+fn init(arg: InitArg) {
+    // Init can't return a candid Result, so an invalid init arg traps and
+    // rolls back deployment instead.
+    if let Err(err) = validate_aggregation_period_secs(arg.aggregation_period_secs) {
+        let msg = match err {
+            Error::InvalidInput { msg } => msg,
+            Error::NotFound { msg } => msg,
+        };
+        ic_cdk::trap(&msg);
+    }
+
+    AGGREGATION_PERIOD.with(|c| {
+        c.borrow_mut()
+            .set(arg.aggregation_period_secs)
+            .expect("Cannot persist aggregation period")
+    });
+    schedule_aggregation_timer(arg.aggregation_period_secs);
+
+    // A fresh deployment starts on the current code's record shapes, so
+    // there is nothing for `migrate()` to do.
+    SCHEMA_VERSION.with(|c| {
+        c.borrow_mut()
+            .set(CURRENT_SCHEMA_VERSION)
+            .expect("Cannot persist schema version")
+    });
+}
+
+// Stable structures (Cell/StableBTreeMap) are backed directly by stable
+// memory and survive an upgrade on their own; nothing needs to be staged
+// here today. Kept as the hook point for anything that does need explicit
+// pre-upgrade handling later (e.g. heap-only state).
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {}
+
+// Timers don't persist across upgrades, so the aggregation timer must be
+// restarted; `migrate()` then brings stored records up to the running
+// code's schema version.
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    let period_secs = AGGREGATION_PERIOD.with(|c| *c.borrow().get());
+    schedule_aggregation_timer(period_secs);
+    migrate();
+}
+
+// Rewrites stored records into the current shape when the persisted schema
+// version is behind the running code, filling defaults for any fields added
+// since that version, then bumps the persisted version.
+fn migrate() {
+    let stored_version = SCHEMA_VERSION.with(|c| *c.borrow().get());
+    if stored_version >= CURRENT_SCHEMA_VERSION {
+        return;
+    }
+
+    // No field additions to `SensorData` yet at schema version 1, so this is
+    // currently an identity rewrite. When a future version adds fields,
+    // branch on `stored_version` here to backfill defaults before
+    // re-inserting.
+    let ids: Vec<u64> = STORAGE.with(|s| s.borrow().iter().map(|(id, _)| id).collect());
+    STORAGE.with(|s| {
+        let mut storage = s.borrow_mut();
+        for id in ids {
+            if let Some(data) = storage.get(&id) {
+                storage.insert(id, data);
+            }
+        }
+    });
+
+    SCHEMA_VERSION.with(|c| {
+        c.borrow_mut()
+            .set(CURRENT_SCHEMA_VERSION)
+            .expect("Cannot persist schema version")
+    });
+}
+
+// Query to read the schema version stored records were last migrated to.
+#[ic_cdk::query]
+fn get_schema_version() -> u32 {
+    SCHEMA_VERSION.with(|c| *c.borrow().get())
+}
+
+// (Re)schedules the periodic aggregation task, cancelling any timer already
+// running so a period change doesn't leave duplicate timers ticking.
+fn schedule_aggregation_timer(period_secs: u64) {
+    AGGREGATION_TIMER.with(|t| {
+        if let Some(timer_id) = t.borrow_mut().take() {
+            ic_cdk_timers::clear_timer(timer_id);
+        }
+    });
+
+    let timer_id =
+        ic_cdk_timers::set_timer_interval(Duration::from_secs(period_secs), run_aggregation);
+    AGGREGATION_TIMER.with(|t| *t.borrow_mut() = Some(timer_id));
+}
+
+// Folds raw readings inserted since the last tick into per-device,
+// per-bucket rollups covering the configured period, merging into whatever
+// is already stored for a bucket rather than rescanning all of STORAGE every
+// time. Buckets are aligned on the period so a bucket still being filled
+// keeps accumulating correctly across ticks.
+fn run_aggregation() {
+    let period_ns = AGGREGATION_PERIOD.with(|c| *c.borrow().get()) * 1_000_000_000;
+    if period_ns == 0 {
+        return;
+    }
+
+    let last_id = LAST_AGGREGATED_ID.with(|c| *c.borrow().get());
+    let start_id = if last_id == UNSET_LAST_AGGREGATED_ID { 0 } else { last_id + 1 };
+
+    let mut sums: std::collections::BTreeMap<(String, u64), (f64, f64, f64, u64)> =
+        std::collections::BTreeMap::new();
+    let mut max_id = last_id;
+
+    STORAGE.with(|s| {
+        for (id, data) in s.borrow().range(start_id..) {
+            let bucket_start = data.timestamp - (data.timestamp % period_ns);
+            let entry = sums
+                .entry((data.device_id.clone(), bucket_start))
+                .or_insert((0.0, 0.0, 0.0, 0));
+            entry.0 += data.temperature;
+            entry.1 += data.humidity;
+            entry.2 += data.soil_moisture;
+            entry.3 += 1;
+            if max_id == UNSET_LAST_AGGREGATED_ID || id > max_id {
+                max_id = id;
+            }
+        }
+    });
+
+    if max_id == last_id {
+        // No new readings since the last tick.
+        return;
+    }
+
+    ROLLUPS.with(|r| {
+        let mut rollups = r.borrow_mut();
+        for ((device_id, bucket_start), (temp_sum, humidity_sum, soil_sum, count)) in sums {
+            let key = RollupKey { device_id: device_id.clone(), bucket_start };
+            let (prev_count, prev_temp, prev_humidity, prev_soil) = match rollups.get(&key) {
+                Some(existing) => (
+                    existing.sample_count,
+                    existing.avg_temperature,
+                    existing.avg_humidity,
+                    existing.avg_soil_moisture,
+                ),
+                None => (0, 0.0, 0.0, 0.0),
+            };
+
+            let combined_count = prev_count + count;
+            let combined_count_f = combined_count as f64;
+            rollups.insert(
+                key,
+                RollupRecord {
+                    device_id,
+                    bucket_start,
+                    avg_temperature: (prev_temp * prev_count as f64 + temp_sum) / combined_count_f,
+                    avg_humidity: (prev_humidity * prev_count as f64 + humidity_sum) / combined_count_f,
+                    avg_soil_moisture: (prev_soil * prev_count as f64 + soil_sum) / combined_count_f,
+                    sample_count: combined_count,
+                },
+            );
+        }
+    });
+
+    LAST_AGGREGATED_ID.with(|c| {
+        c.borrow_mut()
+            .set(max_id)
+            .expect("Cannot persist last aggregated id")
+    });
+}
+
+// Update to change how often the rollup timer runs. Takes effect immediately
+// by cancelling and rescheduling the running timer.
+#[ic_cdk::update]
+fn set_aggregation_period(period_secs: u64) -> Result<(), Error> {
+    validate_aggregation_period_secs(period_secs)?;
+    AGGREGATION_PERIOD.with(|c| {
+        c.borrow_mut()
+            .set(period_secs)
+            .expect("Cannot persist aggregation period")
+    });
+    schedule_aggregation_timer(period_secs);
+    Ok(())
+}
+
+// Bounds an aggregation period so `run_aggregation`'s seconds-to-nanoseconds
+// conversion can't overflow (or silently wrap, in a release build) into a
+// nonsensical bucket width.
+fn validate_aggregation_period_secs(period_secs: u64) -> Result<(), Error> {
+    if period_secs == 0 {
+        return Err(Error::InvalidInput {
+            msg: "Aggregation period must be greater than zero".to_string(),
+        });
+    }
+    if period_secs > MAX_AGGREGATION_PERIOD_SECS {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "Aggregation period must be at most {} seconds",
+                MAX_AGGREGATION_PERIOD_SECS
+            ),
+        });
+    }
+    Ok(())
+}
+
+// Query to read the currently configured aggregation period, in seconds.
+#[ic_cdk::query]
+fn get_aggregation_period() -> u64 {
+    AGGREGATION_PERIOD.with(|c| *c.borrow().get())
 }
 
 // Payload structure for incoming sensor data, used for creating or updating records.
@@ -65,11 +496,32 @@ struct SensorDataPayload {
     soil_moisture: f64,
 }
 
-// Function to validate incoming sensor data payload before processing.
-fn validate_sensor_data_payload(payload: &SensorDataPayload) -> Result<(), Error> {
-    if payload.device_id.trim().is_empty() {
+// Validates a device id against the constraints every composite
+// `(device_id, ...)` stable-memory key relies on: `encode_device_keyed`
+// reserves exactly DEVICE_ID_KEY_LEN bytes for the id with no truncation, so
+// anything that builds one of those keys (directly or via lookup) must run
+// this first, or `encode_device_keyed`'s assert traps the call instead of
+// returning a proper error.
+fn validate_device_id(device_id: &str) -> Result<(), Error> {
+    if device_id.trim().is_empty() {
         return Err(Error::InvalidInput { msg: "Device ID cannot be empty".to_string() });
     }
+    if device_id.as_bytes().len() > DEVICE_ID_KEY_LEN {
+        return Err(Error::InvalidInput {
+            msg: format!("Device ID must be at most {} bytes", DEVICE_ID_KEY_LEN),
+        });
+    }
+    if device_id.contains('\0') {
+        return Err(Error::InvalidInput {
+            msg: "Device ID must not contain NUL bytes".to_string(),
+        });
+    }
+    Ok(())
+}
+
+// Function to validate incoming sensor data payload before processing.
+fn validate_sensor_data_payload(payload: &SensorDataPayload) -> Result<(), Error> {
+    validate_device_id(&payload.device_id)?;
     if !(0.0..=100.0).contains(&payload.humidity) {
         return Err(Error::InvalidInput { msg: "Humidity must be between 0 and 100".to_string() });
     }
@@ -103,7 +555,12 @@ fn _get_sensor_data(id: &u64) -> Option<SensorData> {
 fn add_sensor_data(payload: SensorDataPayload) -> Result<SensorData, Error> {
     // Validate the incoming sensor data payload.
     validate_sensor_data_payload(&payload)?;
+    Ok(create_sensor_data_record(payload))
+}
 
+// Assigns a fresh id to a validated payload, stores it, and returns the
+// resulting record. Shared by `add_sensor_data` and the batch ingestion path.
+fn create_sensor_data_record(payload: SensorDataPayload) -> SensorData {
     let id = ID_COUNTER
         .with(|counter| {
             let current_value = *counter.borrow().get();
@@ -122,12 +579,20 @@ fn add_sensor_data(payload: SensorDataPayload) -> Result<SensorData, Error> {
     };
 
     do_insert(&data);
-    Ok(data)
+    evaluate_alert_rules(&data);
+    data
 }
 
-// Helper method to insert sensor data into stable storage.
+// Helper method to insert sensor data into stable storage, keeping the
+// device_id secondary index in sync.
 fn do_insert(data: &SensorData) {
     STORAGE.with(|service| service.borrow_mut().insert(data.id, data.clone()));
+    DEVICE_INDEX.with(|index| {
+        index.borrow_mut().insert(
+            DeviceIndexKey { device_id: data.device_id.clone(), id: data.id },
+            (),
+        )
+    });
 }
 
 // Update function to modify existing sensor data by ID.
@@ -138,6 +603,8 @@ fn update_sensor_data(id: u64, payload: SensorDataPayload) -> Result<SensorData,
 
     match STORAGE.with(|service| service.borrow().get(&id)) {
         Some(mut data) => {
+            let previous_device_id = data.device_id.clone();
+
             // Update sensor data fields with new values.
             data.device_id = payload.device_id;
             data.temperature = payload.temperature;
@@ -145,6 +612,18 @@ fn update_sensor_data(id: u64, payload: SensorDataPayload) -> Result<SensorData,
             data.soil_moisture = payload.soil_moisture;
             data.updated_at = Some(time());
             do_insert(&data);
+            evaluate_alert_rules(&data);
+
+            // The device_id secondary index is keyed by (device_id, id), so a
+            // device_id change leaves a stale entry under the old key.
+            if previous_device_id != data.device_id {
+                DEVICE_INDEX.with(|index| {
+                    index
+                        .borrow_mut()
+                        .remove(&DeviceIndexKey { device_id: previous_device_id, id })
+                });
+            }
+
             Ok(data)
         }
         None => Err(Error::NotFound {
@@ -160,7 +639,14 @@ fn update_sensor_data(id: u64, payload: SensorDataPayload) -> Result<SensorData,
 #[ic_cdk::update]
 fn delete_sensor_data(id: u64) -> Result<SensorData, Error> {
     match STORAGE.with(|service| service.borrow_mut().remove(&id)) {
-        Some(data) => Ok(data),
+        Some(data) => {
+            DEVICE_INDEX.with(|index| {
+                index
+                    .borrow_mut()
+                    .remove(&DeviceIndexKey { device_id: data.device_id.clone(), id })
+            });
+            Ok(data)
+        }
         None => Err(Error::NotFound {
             msg: format!(
                 "Couldn't delete sensor data with id={}. Data not found.",
@@ -170,6 +656,405 @@ fn delete_sensor_data(id: u64) -> Result<SensorData, Error> {
     }
 }
 
+// Query to page through a device's readings in id order, using the
+// device_id secondary index instead of scanning the whole map. Pass the last
+// id seen as `start_after` to fetch the next page.
+#[ic_cdk::query]
+fn list_device_data(
+    device_id: String,
+    start_after: Option<u64>,
+    limit: u64,
+) -> Result<Vec<SensorData>, Error> {
+    validate_device_id(&device_id)?;
+    if limit == 0 {
+        return Err(Error::InvalidInput {
+            msg: "limit must be greater than zero".to_string(),
+        });
+    }
+
+    let start_id = match start_after {
+        Some(id) => id.checked_add(1).unwrap_or(u64::MAX),
+        None => 0,
+    };
+    let range_start = DeviceIndexKey { device_id: device_id.clone(), id: start_id };
+    let range_end = DeviceIndexKey { device_id: device_id.clone(), id: u64::MAX };
+
+    let ids: Vec<u64> = DEVICE_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(range_start..=range_end)
+            .take(limit as usize)
+            .map(|(key, _)| key.id)
+            .collect()
+    });
+
+    Ok(ids.iter().filter_map(_get_sensor_data).collect())
+}
+
+// Wire format of a bulk ingestion payload.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+enum IngestFormat {
+    Csv,
+    Jsonl,
+}
+
+// A single row that failed to parse or validate during batch ingestion,
+// identified by its 0-based line number in `body` so callers can fix and
+// resubmit just the failed rows.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct RowError {
+    line: u64,
+    msg: String,
+}
+
+// Outcome of a batch ingestion call: how many rows were inserted, and the
+// per-row errors for the rest.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct BatchResult {
+    inserted: u64,
+    errors: Vec<RowError>,
+}
+
+// Update to bulk-ingest readings batched by an IoT gateway. Each row is
+// validated independently so one bad row doesn't fail the whole batch;
+// failures are returned as `RowError`s keyed by line number.
+#[ic_cdk::update]
+fn add_sensor_data_batch(format: IngestFormat, body: String) -> Result<BatchResult, Error> {
+    let rows = match format {
+        IngestFormat::Jsonl => parse_jsonl_payloads(&body),
+        IngestFormat::Csv => parse_csv_payloads(&body),
+    };
+
+    let mut result = BatchResult::default();
+    for (line, parsed) in rows {
+        let payload = match parsed {
+            Ok(payload) => payload,
+            Err(msg) => {
+                result.errors.push(RowError { line, msg });
+                continue;
+            }
+        };
+
+        match validate_sensor_data_payload(&payload) {
+            Ok(()) => {
+                create_sensor_data_record(payload);
+                result.inserted += 1;
+            }
+            Err(Error::InvalidInput { msg }) | Err(Error::NotFound { msg }) => {
+                result.errors.push(RowError { line, msg })
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+// Parses a JSONL body, one `SensorDataPayload` per non-empty line.
+fn parse_jsonl_payloads(body: &str) -> Vec<(u64, Result<SensorDataPayload, String>)> {
+    body.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line, raw)| {
+            let parsed = serde_json::from_str::<SensorDataPayload>(raw.trim())
+                .map_err(|e| format!("invalid JSON: {}", e));
+            (line as u64, parsed)
+        })
+        .collect()
+}
+
+// Parses a CSV body: the header row maps column names to the four payload
+// fields (in any order), and each following non-empty line is a record.
+fn parse_csv_payloads(body: &str) -> Vec<(u64, Result<SensorDataPayload, String>)> {
+    let mut lines = body.lines().enumerate();
+    let header = match lines.next() {
+        Some((_, header)) => header,
+        None => return Vec::new(),
+    };
+
+    let columns: Vec<&str> = header.split(',').map(|c| c.trim()).collect();
+    let find_col =
+        |name: &str| columns.iter().position(|c| c.eq_ignore_ascii_case(name));
+    let device_id_idx = find_col("device_id");
+    let temperature_idx = find_col("temperature");
+    let humidity_idx = find_col("humidity");
+    let soil_moisture_idx = find_col("soil_moisture");
+
+    lines
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line, raw)| {
+            let fields: Vec<&str> = raw.split(',').map(|f| f.trim()).collect();
+            (line as u64, parse_csv_row(&fields, device_id_idx, temperature_idx, humidity_idx, soil_moisture_idx))
+        })
+        .collect()
+}
+
+// Builds one payload out of a split CSV row given the column positions
+// resolved from the header.
+fn parse_csv_row(
+    fields: &[&str],
+    device_id_idx: Option<usize>,
+    temperature_idx: Option<usize>,
+    humidity_idx: Option<usize>,
+    soil_moisture_idx: Option<usize>,
+) -> Result<SensorDataPayload, String> {
+    let device_id_idx = device_id_idx.ok_or("missing device_id column in header")?;
+    let temperature_idx = temperature_idx.ok_or("missing temperature column in header")?;
+    let humidity_idx = humidity_idx.ok_or("missing humidity column in header")?;
+    let soil_moisture_idx = soil_moisture_idx.ok_or("missing soil_moisture column in header")?;
+
+    let device_id = fields
+        .get(device_id_idx)
+        .copied()
+        .ok_or("row missing device_id value")?
+        .to_string();
+    let temperature = fields
+        .get(temperature_idx)
+        .copied()
+        .ok_or("row missing temperature value")?
+        .parse::<f64>()
+        .map_err(|e| format!("invalid temperature: {}", e))?;
+    let humidity = fields
+        .get(humidity_idx)
+        .copied()
+        .ok_or("row missing humidity value")?
+        .parse::<f64>()
+        .map_err(|e| format!("invalid humidity: {}", e))?;
+    let soil_moisture = fields
+        .get(soil_moisture_idx)
+        .copied()
+        .ok_or("row missing soil_moisture value")?
+        .parse::<f64>()
+        .map_err(|e| format!("invalid soil_moisture: {}", e))?;
+
+    Ok(SensorDataPayload { device_id, temperature, humidity, soil_moisture })
+}
+
+// Sensor metric an alert rule watches.
+#[derive(candid::CandidType, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Metric {
+    Temperature,
+    Humidity,
+    SoilMoisture,
+}
+
+impl Default for Metric {
+    fn default() -> Self {
+        Metric::Temperature
+    }
+}
+
+fn metric_value(data: &SensorData, metric: Metric) -> f64 {
+    match metric {
+        Metric::Temperature => data.temperature,
+        Metric::Humidity => data.humidity,
+        Metric::SoilMoisture => data.soil_moisture,
+    }
+}
+
+// A per-device bound on one metric. A reading outside `[min, max]` (either
+// bound may be omitted) raises an alert.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct AlertRule {
+    id: u64,
+    device_id: String,
+    metric: Metric,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Storable for AlertRule {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for AlertRule {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+fn rule_violated(rule: &AlertRule, value: f64) -> bool {
+    if let Some(min) = rule.min {
+        if value < min {
+            return true;
+        }
+    }
+    if let Some(max) = rule.max {
+        if value > max {
+            return true;
+        }
+    }
+    false
+}
+
+// A reading that fell outside a matching rule's bounds.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Alert {
+    rule_id: u64,
+    sensor_data_id: u64,
+    device_id: String,
+    metric: Metric,
+    value: f64,
+    timestamp: u64,
+}
+
+impl Storable for Alert {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Alert {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Composite key for the alert log: device id, then reading timestamp, then
+// alert id to disambiguate same-timestamp alerts. Fixed-width and
+// device-id-prefixed like `RollupKey`/`DeviceIndexKey`, so `get_alerts` can
+// range-scan a device's alerts from a given timestamp onward.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct AlertKey {
+    device_id: String,
+    timestamp: u64,
+    alert_id: u64,
+}
+
+impl Storable for AlertKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(encode_device_keyed(&self.device_id, &[self.timestamp, self.alert_id]))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let (device_id, suffix) = decode_device_keyed(bytes.as_ref());
+        let timestamp = u64::from_be_bytes(suffix[..8].try_into().unwrap());
+        let alert_id = u64::from_be_bytes(suffix[8..].try_into().unwrap());
+        Self { device_id, timestamp, alert_id }
+    }
+}
+
+impl BoundedStorable for AlertKey {
+    const MAX_SIZE: u32 = (DEVICE_ID_KEY_LEN + 16) as u32;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// Update to register a threshold rule for a device/metric. At least one of
+// `min`/`max` must be set.
+#[ic_cdk::update]
+fn register_alert_rule(
+    device_id: String,
+    metric: Metric,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<u64, Error> {
+    validate_device_id(&device_id)?;
+    if min.is_none() && max.is_none() {
+        return Err(Error::InvalidInput {
+            msg: "Alert rule must specify a min and/or a max bound".to_string(),
+        });
+    }
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(Error::InvalidInput { msg: "min must not exceed max".to_string() });
+        }
+    }
+
+    let id = ALERT_RULE_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("Cannot increment alert rule ID counter");
+
+    ALERT_RULES.with(|rules| {
+        rules.borrow_mut().insert(
+            id,
+            AlertRule { id, device_id: device_id.clone(), metric, min, max },
+        )
+    });
+    ALERT_RULE_INDEX.with(|index| {
+        index
+            .borrow_mut()
+            .insert(DeviceIndexKey { device_id, id }, ())
+    });
+
+    Ok(id)
+}
+
+// Evaluates a newly stored reading against every alert rule for its device,
+// appending an `Alert` for each rule whose bounds it violates.
+fn evaluate_alert_rules(data: &SensorData) {
+    let range_start = DeviceIndexKey { device_id: data.device_id.clone(), id: 0 };
+    let range_end = DeviceIndexKey { device_id: data.device_id.clone(), id: u64::MAX };
+    let rule_ids: Vec<u64> = ALERT_RULE_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(range_start..=range_end)
+            .map(|(key, _)| key.id)
+            .collect()
+    });
+
+    let violated: Vec<AlertRule> = ALERT_RULES.with(|rules| {
+        let rules = rules.borrow();
+        rule_ids
+            .iter()
+            .filter_map(|rule_id| rules.get(rule_id))
+            .filter(|rule| rule_violated(rule, metric_value(data, rule.metric)))
+            .collect()
+    });
+
+    for rule in violated {
+        let alert_id = ALERT_ID_COUNTER
+            .with(|counter| {
+                let current_value = *counter.borrow().get();
+                counter.borrow_mut().set(current_value + 1)
+            })
+            .expect("Cannot increment alert ID counter");
+
+        let alert = Alert {
+            rule_id: rule.id,
+            sensor_data_id: data.id,
+            device_id: data.device_id.clone(),
+            metric: rule.metric,
+            value: metric_value(data, rule.metric),
+            timestamp: data.timestamp,
+        };
+
+        ALERTS.with(|alerts| {
+            alerts.borrow_mut().insert(
+                AlertKey { device_id: data.device_id.clone(), timestamp: data.timestamp, alert_id },
+                alert,
+            )
+        });
+    }
+}
+
+// Query to list a device's alerts with a timestamp at or after `since_ts`,
+// oldest first.
+#[ic_cdk::query]
+fn get_alerts(device_id: String, since_ts: u64) -> Result<Vec<Alert>, Error> {
+    validate_device_id(&device_id)?;
+
+    let range_start = AlertKey { device_id: device_id.clone(), timestamp: since_ts, alert_id: 0 };
+    let range_end = AlertKey { device_id, timestamp: u64::MAX, alert_id: u64::MAX };
+
+    Ok(ALERTS.with(|alerts| {
+        alerts
+            .borrow()
+            .range(range_start..=range_end)
+            .map(|(_, alert)| alert)
+            .collect()
+    }))
+}
+
 // Custom error types for validation and not found cases.
 #[derive(candid::CandidType, Deserialize, Serialize)]
 enum Error {
@@ -177,5 +1062,254 @@ enum Error {
     InvalidInput { msg: String },  // Error type for invalid input during validation
 }
 
+// Number of buckets used by each metric histogram. 512 keeps per-histogram
+// memory small while giving sub-percent percentile resolution.
+const HISTOGRAM_BUCKETS: usize = 512;
+
+// Fixed-bucket linear histogram (equal-width buckets over the domain) used to
+// compute percentiles without retaining every sample. Mean/min/max are tracked separately since the
+// histogram only records which bucket a value landed in.
+struct Histogram {
+    domain_min: f64,
+    domain_max: f64,
+    buckets: [u64; HISTOGRAM_BUCKETS],
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Histogram {
+    fn new(domain_min: f64, domain_max: f64) -> Self {
+        Self {
+            domain_min,
+            domain_max,
+            buckets: [0; HISTOGRAM_BUCKETS],
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+
+        let bucket_width = (self.domain_max - self.domain_min) / HISTOGRAM_BUCKETS as f64;
+        let clamped = value.clamp(self.domain_min, self.domain_max);
+        let idx = (((clamped - self.domain_min) / bucket_width) as usize).min(HISTOGRAM_BUCKETS - 1);
+        self.buckets[idx] += 1;
+    }
+
+    // Walks buckets accumulating counts until the cumulative fraction reaches
+    // `p`, then linearly interpolates within that bucket's value range.
+    fn percentile(&self, p: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        let bucket_width = (self.domain_max - self.domain_min) / HISTOGRAM_BUCKETS as f64;
+        let mut cumulative: u64 = 0;
+        for (i, &bucket_count) in self.buckets.iter().enumerate() {
+            let prev_cumulative = cumulative;
+            cumulative += bucket_count;
+            if cumulative as f64 / self.count as f64 >= p {
+                let bucket_start = self.domain_min + i as f64 * bucket_width;
+                let within = if bucket_count > 0 {
+                    let target = p * self.count as f64 - prev_cumulative as f64;
+                    (target / bucket_count as f64).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                return bucket_start + within * bucket_width;
+            }
+        }
+        self.domain_max
+    }
+
+    fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    fn to_metric_stats(&self) -> MetricStats {
+        MetricStats {
+            min: if self.count == 0 { 0.0 } else { self.min },
+            max: if self.count == 0 { 0.0 } else { self.max },
+            mean: self.mean(),
+            p50: self.percentile(0.50),
+            p95: self.percentile(0.95),
+            p99: self.percentile(0.99),
+        }
+    }
+}
+
+// Min/max/mean plus tail percentiles for a single metric over a time window.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct MetricStats {
+    min: f64,
+    max: f64,
+    mean: f64,
+    p50: f64,
+    p95: f64,
+    p99: f64,
+}
+
+// Rollup of temperature/humidity/soil_moisture stats for a device over a
+// `[start_ts, end_ts]` window, suitable for cheap dashboard rendering.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct DeviceStats {
+    device_id: String,
+    sample_count: u64,
+    temperature: MetricStats,
+    humidity: MetricStats,
+    soil_moisture: MetricStats,
+}
+
+// Query to compute min/max/mean/p50/p95/p99 for a device's readings within a
+// timestamp window, using fixed-bucket histograms to bound memory use.
+#[ic_cdk::query]
+fn aggregate_device_stats(
+    device_id: String,
+    start_ts: u64,
+    end_ts: u64,
+) -> Result<DeviceStats, Error> {
+    let mut temperature = Histogram::new(-50.0, 60.0);
+    let mut humidity = Histogram::new(0.0, 100.0);
+    let mut soil_moisture = Histogram::new(0.0, 100.0);
+    let mut sample_count: u64 = 0;
+
+    STORAGE.with(|s| {
+        for (_, data) in s.borrow().iter() {
+            if data.device_id != device_id {
+                continue;
+            }
+            if data.timestamp < start_ts || data.timestamp > end_ts {
+                continue;
+            }
+            temperature.add(data.temperature);
+            humidity.add(data.humidity);
+            soil_moisture.add(data.soil_moisture);
+            sample_count += 1;
+        }
+    });
+
+    if sample_count == 0 {
+        return Err(Error::NotFound {
+            msg: format!(
+                "No sensor data for device_id={} in range [{}, {}]",
+                device_id, start_ts, end_ts
+            ),
+        });
+    }
+
+    Ok(DeviceStats {
+        device_id,
+        sample_count,
+        temperature: temperature.to_metric_stats(),
+        humidity: humidity.to_metric_stats(),
+        soil_moisture: soil_moisture.to_metric_stats(),
+    })
+}
+
+#[cfg(test)]
+mod histogram_tests {
+    use super::Histogram;
+
+    #[test]
+    fn percentile_of_empty_histogram_is_zero() {
+        let histogram = Histogram::new(0.0, 100.0);
+        assert_eq!(histogram.percentile(0.50), 0.0);
+    }
+
+    #[test]
+    fn percentile_interpolates_within_a_bucket() {
+        let mut histogram = Histogram::new(0.0, 100.0);
+        for value in 1..=100 {
+            histogram.add(value as f64);
+        }
+        assert!((histogram.percentile(0.50) - 50.0).abs() < 1.0);
+        assert!((histogram.percentile(0.99) - 99.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn histogram_tracks_min_max_and_mean_separately_from_buckets() {
+        let mut histogram = Histogram::new(0.0, 100.0);
+        histogram.add(10.0);
+        histogram.add(20.0);
+        histogram.add(30.0);
+        let stats = histogram.to_metric_stats();
+        assert_eq!(stats.min, 10.0);
+        assert_eq!(stats.max, 30.0);
+        assert_eq!(stats.mean, 20.0);
+    }
+
+    #[test]
+    fn values_outside_the_domain_are_clamped_into_the_edge_buckets() {
+        let mut histogram = Histogram::new(0.0, 100.0);
+        histogram.add(-50.0);
+        histogram.add(1000.0);
+        assert!((histogram.percentile(1.0) - 100.0).abs() < 0.5);
+    }
+}
+
+#[cfg(test)]
+mod batch_ingest_tests {
+    use super::{parse_csv_payloads, parse_jsonl_payloads};
+
+    #[test]
+    fn jsonl_parses_one_payload_per_non_empty_line() {
+        let body = "{\"device_id\":\"a\",\"temperature\":21.0,\"humidity\":40.0,\"soil_moisture\":30.0}\n\n{\"device_id\":\"b\",\"temperature\":22.0,\"humidity\":41.0,\"soil_moisture\":31.0}";
+        let rows = parse_jsonl_payloads(body);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 0);
+        assert_eq!(rows[0].1.as_ref().unwrap().device_id, "a");
+        assert_eq!(rows[1].0, 2);
+        assert_eq!(rows[1].1.as_ref().unwrap().device_id, "b");
+    }
+
+    #[test]
+    fn jsonl_reports_an_error_for_an_invalid_line_without_failing_the_batch() {
+        let body = "not json";
+        let rows = parse_jsonl_payloads(body);
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].1.is_err());
+    }
+
+    #[test]
+    fn csv_maps_columns_by_header_name_in_any_order() {
+        let body = "humidity,device_id,soil_moisture,temperature\n40.0,a,30.0,21.0";
+        let rows = parse_csv_payloads(body);
+        assert_eq!(rows.len(), 1);
+        let payload = rows[0].1.as_ref().unwrap();
+        assert_eq!(payload.device_id, "a");
+        assert_eq!(payload.temperature, 21.0);
+        assert_eq!(payload.humidity, 40.0);
+        assert_eq!(payload.soil_moisture, 30.0);
+    }
+
+    #[test]
+    fn csv_reports_an_error_for_a_non_numeric_field() {
+        let body = "device_id,temperature,humidity,soil_moisture\na,not-a-number,40.0,30.0";
+        let rows = parse_csv_payloads(body);
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].1.is_err());
+    }
+
+    #[test]
+    fn csv_reports_an_error_when_the_header_is_missing_a_required_column() {
+        let body = "device_id,temperature,humidity\na,21.0,40.0";
+        let rows = parse_csv_payloads(body);
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].1.is_err());
+    }
+}
+
 // Generate candid interface for the code.
 ic_cdk::export_candid!();